@@ -0,0 +1,308 @@
+//! Display enumeration and per-display, optionally-cropped capture
+//! threads.
+//!
+//! The server can have more than one thing capturing at once (e.g. two
+//! clients watching two different monitors), so capture threads are
+//! spawned lazily, keyed by the exact `(display, region)` a subscriber
+//! asked for, and shared between subscribers asking for the same thing.
+//!
+//! Capture threads broadcast the raw decoded RGBA frame rather than a
+//! pre-encoded JPEG: re-encoding is comparatively expensive, and every
+//! output (MJPEG, RTP, recording, terminal preview) wants it done to its
+//! own size/quality, so that step happens once per *consumer*, not once
+//! per frame in the capture thread.
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageBuffer, Rgba};
+use scrap::{Capturer, Display};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::{thread, time::Duration};
+use tokio::sync::broadcast;
+
+/// A crop rectangle in source-frame pixel coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CaptureRegion {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+/// Which display to capture and, optionally, which sub-region of it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CaptureConfig {
+    pub display_index: usize,
+    pub region: Option<CaptureRegion>,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self { display_index: 0, region: None }
+    }
+}
+
+pub struct DisplayInfo {
+    pub index: usize,
+    pub width: usize,
+    pub height: usize,
+    pub is_primary: bool,
+}
+
+/// One decoded frame as broadcast by a capture thread: plain RGBA8 at
+/// whatever size capture (and any crop) produced.
+pub struct RawFrame {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+impl RawFrame {
+    pub fn to_dynamic_image(&self) -> Option<DynamicImage> {
+        ImageBuffer::<Rgba<u8>, _>::from_raw(self.width, self.height, self.rgba.clone())
+            .map(DynamicImage::ImageRgba8)
+    }
+
+    /// Encode this frame as JPEG, downscaling to `width`x`height` first
+    /// if given and different from the frame's native size. Shared by
+    /// every output that needs a JPEG: the MJPEG route picks its own
+    /// per-client size/quality, RTP always wants native size.
+    pub fn encode_jpeg(&self, width: Option<u32>, height: Option<u32>, quality: u8) -> Option<Vec<u8>> {
+        let img = self.to_dynamic_image()?;
+        let img = match (width, height) {
+            (Some(w), Some(h)) if w != self.width || h != self.height => {
+                img.resize_exact(w.max(1), h.max(1), image::imageops::FilterType::Triangle)
+            }
+            _ => img,
+        };
+
+        let mut jpeg_data = Vec::new();
+        let mut encoder = JpegEncoder::new_with_quality(&mut jpeg_data, quality);
+        if let Err(e) = encoder.encode_image(&img) {
+            eprintln!("JPEG encode error: {:?}", e);
+            return None;
+        }
+        Some(jpeg_data)
+    }
+}
+
+/// Shared, cheaply-cloneable broadcast sender for a capture thread's
+/// frames.
+pub type FrameTx = broadcast::Sender<Arc<RawFrame>>;
+
+/// List the displays `scrap` can see, in the same order `--display N` /
+/// `?display=N` index into.
+pub fn list_displays() -> Vec<DisplayInfo> {
+    let displays = match Display::all() {
+        Ok(displays) => displays,
+        Err(e) => {
+            eprintln!("Failed to enumerate displays: {:?}", e);
+            return Vec::new();
+        }
+    };
+
+    displays
+        .iter()
+        .enumerate()
+        .map(|(index, d)| DisplayInfo {
+            index,
+            width: d.width(),
+            height: d.height(),
+            is_primary: d.is_primary(),
+        })
+        .collect()
+}
+
+/// Clamp a requested region's origin and size to the source frame's
+/// bounds. Shared by `crop_bgra` and by callers that need to know the
+/// *effective* output size before cropping happens.
+fn clamp_region(stride_w: usize, stride_h: usize, region: &CaptureRegion) -> (usize, usize, usize, usize) {
+    let x = region.x.min(stride_w);
+    let y = region.y.min(stride_h);
+    let w = region.w.min(stride_w - x);
+    let h = region.h.min(stride_h - y);
+    (x, y, w, h)
+}
+
+/// Slice a `{x, y, w, h}` region out of a raw BGRA frame, respecting the
+/// source stride (`stride_w * 4`), without allocating the full-size
+/// frame first. The region is clamped to the source bounds first, so
+/// callers must use `clamp_region` to learn the effective width/height
+/// rather than assuming the requested `region.w`/`region.h` came back
+/// unchanged.
+fn crop_bgra(frame: &[u8], stride_w: usize, stride_h: usize, region: &CaptureRegion) -> Vec<u8> {
+    let (x, y, w, h) = clamp_region(stride_w, stride_h, region);
+
+    let mut out = Vec::with_capacity(w * h * 4);
+    let row_bytes = w * 4;
+    let stride_bytes = stride_w * 4;
+
+    for row in 0..h {
+        let row_start = (y + row) * stride_bytes + x * 4;
+        out.extend_from_slice(&frame[row_start..row_start + row_bytes]);
+    }
+
+    out
+}
+
+/// Lazily spawns and shares capture threads keyed by `CaptureConfig`, so
+/// two subscribers asking for the same display/region get one capture
+/// loop between them instead of duplicating capture work.
+#[derive(Clone, Default)]
+pub struct CaptureManager {
+    channels: Arc<Mutex<HashMap<CaptureConfig, FrameTx>>>,
+}
+
+impl CaptureManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the broadcast sender for this config, spawning its capture
+    /// thread on first use.
+    pub fn get_or_spawn(&self, config: CaptureConfig) -> FrameTx {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(tx) = channels.get(&config) {
+            return tx.clone();
+        }
+
+        let (frame_tx, _) = broadcast::channel::<Arc<RawFrame>>(16);
+        spawn_capture_thread(config, frame_tx.clone());
+        channels.insert(config, frame_tx.clone());
+        frame_tx
+    }
+}
+
+fn spawn_capture_thread(config: CaptureConfig, frame_tx: FrameTx) {
+    thread::spawn(move || {
+        let displays = match Display::all() {
+            Ok(displays) => displays,
+            Err(e) => {
+                eprintln!("Failed to enumerate displays: {:?}", e);
+                return;
+            }
+        };
+
+        let display = match displays.into_iter().nth(config.display_index) {
+            Some(d) => d,
+            None => {
+                eprintln!("No display at index {}.", config.display_index);
+                return;
+            }
+        };
+
+        let mut capturer = match Capturer::new(display) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Failed to begin capture: {:?}", e);
+                return;
+            }
+        };
+        let (w, h) = (capturer.width(), capturer.height());
+        let (out_w, out_h) = match config.region {
+            Some(region) => {
+                let (_, _, cw, ch) = clamp_region(w, h, &region);
+                (cw, ch)
+            }
+            None => (w, h),
+        };
+        println!(
+            "Capturing display {} ({}x{}){}...",
+            config.display_index,
+            out_w,
+            out_h,
+            if config.region.is_some() { ", cropped" } else { "" }
+        );
+
+        loop {
+            match capturer.frame() {
+                Ok(frame) => {
+                    if frame.len() != (w * h * 4) {
+                        eprintln!("Unexpected frame size.");
+                        continue;
+                    }
+
+                    let bgra = match &config.region {
+                        Some(region) => crop_bgra(&frame, w, h, region),
+                        None => frame.to_vec(),
+                    };
+
+                    // Convert BGRA to RGBA
+                    let mut rgba_frame = Vec::with_capacity(bgra.len());
+                    for chunk in bgra.chunks_exact(4) {
+                        rgba_frame.extend_from_slice(&[chunk[2], chunk[1], chunk[0], chunk[3]]);
+                    }
+
+                    let _ = frame_tx.send(Arc::new(RawFrame {
+                        width: out_w as u32,
+                        height: out_h as u32,
+                        rgba: rgba_frame,
+                    }));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(16)); // ~60 FPS
+                }
+                Err(e) => {
+                    eprintln!("Error capturing frame: {:?}", e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_region_fits_inside_bounds() {
+        let region = CaptureRegion { x: 10, y: 20, w: 100, h: 50 };
+        assert_eq!(clamp_region(800, 600, &region), (10, 20, 100, 50));
+    }
+
+    #[test]
+    fn clamp_region_shrinks_size_that_overruns_bounds() {
+        let region = CaptureRegion { x: 700, y: 580, w: 200, h: 200 };
+        assert_eq!(clamp_region(800, 600, &region), (700, 580, 100, 20));
+    }
+
+    #[test]
+    fn clamp_region_clamps_an_origin_past_the_edge_to_zero_size() {
+        let region = CaptureRegion { x: 900, y: 50, w: 10, h: 10 };
+        assert_eq!(clamp_region(800, 600, &region), (800, 50, 0, 10));
+    }
+
+    #[test]
+    fn crop_bgra_slices_out_the_requested_rows() {
+        // 4x2 source frame, BGRA, each pixel's blue channel equal to its
+        // column so the cropped columns are easy to verify.
+        let stride_w = 4;
+        let stride_h = 2;
+        let mut frame = Vec::new();
+        for _row in 0..stride_h {
+            for col in 0..stride_w {
+                frame.extend_from_slice(&[col as u8, 0, 0, 255]);
+            }
+        }
+
+        let region = CaptureRegion { x: 1, y: 0, w: 2, h: 1 };
+        let cropped = crop_bgra(&frame, stride_w, stride_h, &region);
+
+        assert_eq!(cropped, vec![1, 0, 0, 255, 2, 0, 0, 255]);
+    }
+
+    #[test]
+    fn crop_bgra_honors_clamping_for_an_oversized_region() {
+        let stride_w = 2;
+        let stride_h = 2;
+        let frame: Vec<u8> = (0..stride_w * stride_h * 4).map(|i| i as u8).collect();
+
+        // Requests the whole frame plus more; clamp_region should bring it
+        // back to the 2x2 source, so the crop is just the original frame.
+        let region = CaptureRegion { x: 0, y: 0, w: 10, h: 10 };
+        let cropped = crop_bgra(&frame, stride_w, stride_h, &region);
+
+        assert_eq!(cropped, frame);
+    }
+}
@@ -0,0 +1,18 @@
+//! Small helpers shared by the `ffmpeg-next`-based encode paths
+//! (H.264 recording and VP8/VP9 RTP).
+
+use ffmpeg_next as ffmpeg;
+
+/// Copy a tightly-packed RGBA buffer into an ffmpeg plane, honoring its
+/// (possibly alignment-padded) linesize rather than assuming it matches
+/// the source stride.
+pub(crate) fn copy_rgba_into_plane(rgba_frame: &mut ffmpeg::frame::Video, rgba: &[u8], w: u32, h: u32) {
+    let src_stride = w as usize * 4;
+    let dst_stride = rgba_frame.stride(0);
+    let data = rgba_frame.data_mut(0);
+    for row in 0..h as usize {
+        let src = &rgba[row * src_stride..row * src_stride + src_stride];
+        let dst = &mut data[row * dst_stride..row * dst_stride + src_stride];
+        dst.copy_from_slice(src);
+    }
+}
@@ -1,74 +1,382 @@
+mod capture;
+mod ffmpeg_util;
+mod record;
+mod rtp;
+mod term;
+mod vp8;
+
 use async_stream::stream;
 use bytes::Bytes;
-use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageBuffer, Rgba};
-use scrap::{Capturer, Display};
-use std::{convert::Infallible, thread, time::Duration};
+use capture::{CaptureConfig, CaptureManager, CaptureRegion};
+use record::{Container, RecordingManager};
+use rtp::RtpJpegSender;
+use serde::Deserialize;
+use std::io::Write;
+use std::{convert::Infallible, net::SocketAddr, path::PathBuf, time::Duration};
+use term::{TermConfig, TermProtocol};
 use tokio::sync::broadcast;
-use warp::Filter;
+use vp8::{VpCodec, VpEncoder, VpRtpSender};
+use warp::{http::StatusCode, Filter};
+
+/// How often to force a keyframe on the VP8/VP9 RTP output as a simple
+/// stand-in for real RTCP loss feedback, which we don't have.
+const VP8_KEYFRAME_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Nominal capture rate (the capture thread polls about every 16 ms while
+/// no new frame is ready); used to advance the RTP clocks, as the encoder
+/// time base, and as the default per-client fps cap.
+const CAPTURE_FPS: u32 = 60;
+
+/// Parse `--rtp <host:port>` from argv, enabling the RFC 2435 RTP/JPEG
+/// output alongside the existing MJPEG-over-HTTP route. RFC 2435 packs
+/// dimensions as 8-pixel block counts in a single byte each, capping
+/// the usable resolution at 2040x2040 — bigger captures (1440p, 4K)
+/// will have every frame silently dropped by this output; use `--display`
+/// to stay under that cap. This output always sends the whole default
+/// display: the `/stream` route's `crop_*` params do not apply to it.
+fn parse_rtp_dest() -> Option<SocketAddr> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--rtp" {
+            return args.next().and_then(|addr| addr.parse().ok());
+        }
+    }
+    None
+}
+
+/// Parse `--vp8 <host:port>`, enabling the inter-frame-compressed
+/// VP8-over-RTP output alongside MJPEG and RFC 2435 RTP. `--vp8-bitrate`
+/// overrides the target bitrate in bits/sec. `--vp8-codec vp9` is accepted
+/// for symmetry with the codec name but rejected here at startup: VP9 has
+/// its own RTP payload format that `VpRtpSender` doesn't implement, so the
+/// output is skipped rather than silently failing per-frame.
+fn parse_vp8_dest() -> Option<SocketAddr> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--vp8" {
+            return args.next().and_then(|addr| addr.parse().ok());
+        }
+    }
+    None
+}
+
+/// Parse `--display N`, the default display index used when a stream
+/// request doesn't specify `?display=`.
+fn parse_default_display() -> usize {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--display" {
+            return args.next().and_then(|n| n.parse().ok()).unwrap_or(0);
+        }
+    }
+    0
+}
+
+/// Parse `--record <path>`, starting a recording of the default display
+/// immediately instead of waiting for `POST /record/start`.
+fn parse_record_path() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--record" {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
+}
+
+/// Body of `POST /record/start`.
+#[derive(Deserialize)]
+struct RecordStartRequest {
+    path: String,
+    /// Defaults to whatever the path's extension implies.
+    container: Option<String>,
+}
+
+/// Find `--flag <value>` in argv and parse it, for the handful of flags
+/// that take a single typed value.
+fn parse_flag<T: std::str::FromStr>(flag: &str) -> Option<T> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next().and_then(|v| v.parse().ok());
+        }
+    }
+    None
+}
+
+/// Parse `--term <kitty|sixel>` plus the optional `--term-cols`,
+/// `--term-rows`, `--cell-w`, `--cell-h` flags, enabling a headless
+/// terminal preview sink fed from the default display's capture.
+fn parse_term_config() -> Option<TermConfig> {
+    let protocol = match parse_flag::<String>("--term")?.as_str() {
+        "sixel" => TermProtocol::Sixel,
+        _ => TermProtocol::Kitty,
+    };
+
+    Some(TermConfig {
+        cols: parse_flag("--term-cols").unwrap_or(80),
+        rows: parse_flag("--term-rows").unwrap_or(24),
+        cell_pixel_w: parse_flag("--cell-w").unwrap_or(8),
+        cell_pixel_h: parse_flag("--cell-h").unwrap_or(16),
+        protocol,
+    })
+}
+
+/// Default JPEG quality, matching the `image` crate's own encoder
+/// default; used whenever a client doesn't request one explicitly.
+const DEFAULT_JPEG_QUALITY: u8 = 75;
+
+/// Query parameters accepted by the `/stream` route: which display to
+/// capture and an optional crop rectangle (shared by every subscriber
+/// of that display/region), plus this client's own resolution, frame
+/// rate and JPEG quality caps.
+#[derive(Deserialize)]
+struct StreamQuery {
+    display: Option<usize>,
+    crop_x: Option<usize>,
+    crop_y: Option<usize>,
+    crop_w: Option<usize>,
+    crop_h: Option<usize>,
+    w: Option<u32>,
+    h: Option<u32>,
+    fps: Option<u32>,
+    quality: Option<u8>,
+}
+
+impl StreamQuery {
+    fn capture_config(&self, default_display: usize) -> CaptureConfig {
+        let region = match (self.crop_x, self.crop_y, self.crop_w, self.crop_h) {
+            (Some(x), Some(y), Some(w), Some(h)) => Some(CaptureRegion { x, y, w, h }),
+            _ => None,
+        };
+        CaptureConfig {
+            display_index: self.display.unwrap_or(default_display),
+            region,
+        }
+    }
+
+    fn client_params(&self) -> ClientParams {
+        ClientParams {
+            width: self.w,
+            height: self.h,
+            fps: self.fps.unwrap_or(CAPTURE_FPS).max(1),
+            quality: self.quality.unwrap_or(DEFAULT_JPEG_QUALITY),
+        }
+    }
+}
+
+/// Per-subscriber caps, applied independently so one client asking for
+/// a small, low-quality, low-fps stream doesn't affect any other.
+struct ClientParams {
+    width: Option<u32>,
+    height: Option<u32>,
+    fps: u32,
+    quality: u8,
+}
 
 #[tokio::main]
 async fn main() {
-    let (frame_tx, _) = broadcast::channel::<Vec<u8>>(16);
+    let default_display = parse_default_display();
+    let displays = capture::list_displays();
+    for d in &displays {
+        println!(
+            "Display {}: {}x{}{}",
+            d.index,
+            d.width,
+            d.height,
+            if d.is_primary { " (primary)" } else { "" }
+        );
+    }
 
-    // Spawn a dedicated thread for capturing the screen.
-    {
-        let frame_tx = frame_tx.clone();
-        thread::spawn(move || {
-            let display = Display::primary().expect("Failed to get primary display.");
-            let mut capturer = Capturer::new(display).expect("Failed to begin capture.");
-            let (w, h) = (capturer.width(), capturer.height());
-            println!("Capturing screen ({}x{})...", w, h);
+    let capture_manager = CaptureManager::new();
+    let frame_tx = capture_manager.get_or_spawn(CaptureConfig {
+        display_index: default_display,
+        region: None,
+    });
 
-            loop {
-                match capturer.frame() {
-                    Ok(frame) => {
-                        if frame.len() != (w * h * 4) {
-                            eprintln!("Unexpected frame size.");
-                            continue;
-                        }
+    // Optional RTP/JPEG output, fed from the same broadcast channel as
+    // the HTTP MJPEG route.
+    if let Some(dest) = parse_rtp_dest() {
+        // RFC 2435 packs width/height as one-byte 8-pixel block counts,
+        // capping the usable resolution at 2040x2040 — warn once up
+        // front instead of leaving the operator to notice a dead stream
+        // from nothing but per-frame skip logs.
+        if let Some(d) = displays.iter().find(|d| d.index == default_display) {
+            if d.width > 2040 || d.height > 2040 {
+                eprintln!(
+                    "Warning: RTP/JPEG (RFC 2435) caps resolution at 2040x2040; display {} is \
+                     {}x{}, so this output will drop every frame. Pick a smaller display with \
+                     --display.",
+                    default_display, d.width, d.height
+                );
+            }
+        }
 
-                        // Convert BGRA to RGBA
-                        let mut rgba_frame = Vec::with_capacity(w * h * 4);
-                        for chunk in frame.chunks_exact(4) {
-                            rgba_frame.extend_from_slice(&[chunk[2], chunk[1], chunk[0], chunk[3]]);
+        let mut rx = frame_tx.subscribe();
+        match RtpJpegSender::new(dest, CAPTURE_FPS) {
+            Ok(mut sender) => {
+                println!("Streaming RTP/JPEG to {}", dest);
+                tokio::spawn(async move {
+                    loop {
+                        match rx.recv().await {
+                            Ok(frame) => {
+                                if let Err(e) = sender.send_frame(&frame) {
+                                    eprintln!("RTP send error: {:?}", e);
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => break,
                         }
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to start RTP output: {:?}", e),
+        }
+    }
+
+    // Optional VP8/VP9-over-RTP output: inter-frame compressed, so
+    // unlike the JPEG paths this needs its own stateful encoder fed
+    // frame-by-frame, with a keyframe forced on startup and periodically
+    // thereafter since we have no RTCP loss feedback to react to.
+    if let Some(dest) = parse_vp8_dest() {
+        let codec = match parse_flag::<String>("--vp8-codec").as_deref() {
+            Some("vp9") => VpCodec::Vp9,
+            _ => VpCodec::Vp8,
+        };
+
+        // VP9 has its own RTP payload format that VpRtpSender doesn't
+        // implement (see its doc comment); fail fast and skip this output
+        // entirely rather than letting VpRtpSender::new reject it after
+        // we've already committed to this flag combination.
+        if codec == VpCodec::Vp9 {
+            eprintln!("--vp8-codec vp9 is not supported for the RTP output (--vp8); use VP8, or drop --vp8-codec");
+        } else {
+            let bitrate: usize = parse_flag("--vp8-bitrate").unwrap_or(1_000_000);
+            let mut rx = frame_tx.subscribe();
 
-                        if let Some(img_buf) = ImageBuffer::<Rgba<u8>, _>::from_raw(w as u32, h as u32, rgba_frame) {
-                            let dyn_img = DynamicImage::ImageRgba8(img_buf);
-                            let mut jpeg_data = Vec::new();
-                            {
-                                let mut encoder = JpegEncoder::new(&mut jpeg_data);
-                                if let Err(e) = encoder.encode_image(&dyn_img) {
-                                    eprintln!("JPEG encode error: {:?}", e);
-                                    continue;
+            match VpRtpSender::new(dest, codec, CAPTURE_FPS) {
+                Ok(mut sender) => {
+                    println!("Streaming {:?}/RTP to {}", codec, dest);
+                    tokio::spawn(async move {
+                        let mut encoder: Option<VpEncoder> = None;
+                        let mut last_keyframe = tokio::time::Instant::now();
+
+                        loop {
+                            match rx.recv().await {
+                                Ok(frame) => {
+                                    let enc = match &mut encoder {
+                                        Some(enc) => enc,
+                                        None => {
+                                            match VpEncoder::new(codec, frame.width, frame.height, CAPTURE_FPS, bitrate) {
+                                                Ok(enc) => encoder.insert(enc),
+                                                Err(e) => {
+                                                    eprintln!("Failed to start VP8/VP9 encoder: {:?}", e);
+                                                    continue;
+                                                }
+                                            }
+                                        }
+                                    };
+
+                                    if last_keyframe.elapsed() > VP8_KEYFRAME_INTERVAL {
+                                        enc.force_keyframe();
+                                        last_keyframe = tokio::time::Instant::now();
+                                    }
+
+                                    for chunk in enc.encode(&frame) {
+                                        if let Err(e) = sender.send_chunk(&chunk) {
+                                            eprintln!("VP8/VP9 RTP send error: {:?}", e);
+                                        }
+                                    }
                                 }
+                                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                                Err(broadcast::error::RecvError::Closed) => break,
                             }
-                            let _ = frame_tx.send(jpeg_data);
                         }
-                    }
-                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                        thread::sleep(Duration::from_millis(16)); // ~60 FPS
-                    }
-                    Err(e) => {
-                        eprintln!("Error capturing frame: {:?}", e);
-                        break;
-                    }
+                    });
+                }
+                Err(e) => eprintln!("Failed to start VP8/VP9 RTP output: {:?}", e),
+            }
+        }
+    }
+
+    // Recording sink: takes the same broadcast raw frames and muxes
+    // them to disk as H.264, toggled by flag or by the /record routes.
+    let recording_manager = RecordingManager::new();
+    if let Some(path) = parse_record_path() {
+        let container = Container::from_path(&path);
+        match recording_manager.start(&frame_tx, path, container, CAPTURE_FPS) {
+            Ok(()) => println!("Recording to disk..."),
+            Err(e) => eprintln!("Failed to start recording: {}", e),
+        }
+    }
+
+    // Headless terminal preview: renders the default display's frames
+    // as Kitty graphics or sixel escape sequences straight to stdout,
+    // for use over SSH on a box with no browser.
+    if let Some(term_config) = parse_term_config() {
+        let mut rx = frame_tx.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(frame) => match frame.to_dynamic_image() {
+                        Some(img) => {
+                            print!("{}", term::render_frame(&img, &term_config));
+                            let _ = std::io::stdout().flush();
+                        }
+                        None => eprintln!("Terminal preview: failed to build image from raw frame"),
+                    },
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
                 }
             }
         });
     }
 
-    // Define the MJPEG streaming route.
+    // Define the MJPEG streaming route. Each request can ask for a
+    // different display and/or crop region via query parameters (shared
+    // between subscribers of that capture thread), plus its own
+    // resolution/fps/quality caps applied only to this subscriber's
+    // encode, since re-encoding per client is too expensive to do in
+    // the capture thread itself.
     let stream_route = warp::path("stream")
         .and(warp::get())
+        .and(warp::query::<StreamQuery>())
         .map({
-            let frame_tx = frame_tx.clone();
-            move || {
-                let mut rx = frame_tx.subscribe();
+            let capture_manager = capture_manager.clone();
+            move |query: StreamQuery| {
+                let config = query.capture_config(default_display);
+                let client = query.client_params();
+                let mut rx = capture_manager.get_or_spawn(config).subscribe();
                 let mjpeg_stream = stream! {
+                    let frame_interval = Duration::from_millis(1000 / client.fps as u64);
+                    let mut next_due = tokio::time::Instant::now();
+
                     loop {
                         match rx.recv().await {
-                            Ok(jpeg_data) => {
+                            Ok(frame) => {
+                                let now = tokio::time::Instant::now();
+                                if now < next_due {
+                                    continue; // this client's own rate cap, drop the frame
+                                }
+                                next_due = now + frame_interval;
+
+                                // Resize + JPEG encode is CPU-bound; run it off the
+                                // Tokio reactor so one busy client can't stall every
+                                // other connection's poll.
+                                let frame = frame.clone();
+                                let (width, height, quality) = (client.width, client.height, client.quality);
+                                let encode = tokio::task::spawn_blocking(move || {
+                                    frame.encode_jpeg(width, height, quality)
+                                });
+                                let jpeg_data = match encode.await {
+                                    Ok(Some(data)) => data,
+                                    Ok(None) => continue,
+                                    Err(e) => {
+                                        eprintln!("MJPEG encode task panicked: {:?}", e);
+                                        continue;
+                                    }
+                                };
+
                                 let header = format!(
                                     "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
                                     jpeg_data.len()
@@ -128,7 +436,42 @@ async fn main() {
         )
     });
 
-    let routes = index_route.or(stream_route);
+    // Start/stop on-disk recording of the default display.
+    let record_start_route = warp::path!("record" / "start")
+        .and(warp::post())
+        .and(warp::body::json())
+        .map({
+            let recording_manager = recording_manager.clone();
+            let frame_tx = frame_tx.clone();
+            move |req: RecordStartRequest| {
+                let path = PathBuf::from(req.path);
+                let container = match req.container.as_deref() {
+                    Some("mkv") => Container::Mkv,
+                    Some("mp4") => Container::Mp4,
+                    _ => Container::from_path(&path),
+                };
+                match recording_manager.start(&frame_tx, path, container, CAPTURE_FPS) {
+                    Ok(()) => warp::reply::with_status("recording started".to_string(), StatusCode::OK),
+                    Err(e) => warp::reply::with_status(e, StatusCode::CONFLICT),
+                }
+            }
+        });
+
+    let record_stop_route = warp::path!("record" / "stop").and(warp::post()).map({
+        let recording_manager = recording_manager.clone();
+        move || match recording_manager.stop() {
+            Ok(path) => warp::reply::with_status(
+                format!("recording stopped: {}", path.display()),
+                StatusCode::OK,
+            ),
+            Err(e) => warp::reply::with_status(e, StatusCode::CONFLICT),
+        }
+    });
+
+    let routes = index_route
+        .or(stream_route)
+        .or(record_start_route)
+        .or(record_stop_route);
 
     println!("Server running at http://0.0.0.0:3030/");
     warp::serve(routes).run(([0, 0, 0, 0], 3030)).await;
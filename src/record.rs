@@ -0,0 +1,264 @@
+//! On-disk MP4/Matroska recording, running alongside the live MJPEG/RTP
+//! output.
+//!
+//! MJPEG is a poor archival format (huge, intra-only), so the recording
+//! sink takes the broadcast raw RGBA frames and encodes them as H.264
+//! via `ffmpeg-next`, muxed into a fragmented MP4 (or Matroska)
+//! container so the file on disk stays playable even if the process is
+//! killed mid-recording.
+
+use crate::capture::{FrameTx, RawFrame};
+use crate::ffmpeg_util::copy_rgba_into_plane;
+use ffmpeg_next as ffmpeg;
+use image::GenericImageView;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    Mp4,
+    Mkv,
+}
+
+impl Container {
+    /// Pick the container from a path's extension, defaulting to MP4.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("mkv") => Container::Mkv,
+            _ => Container::Mp4,
+        }
+    }
+
+    fn format_name(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mkv => "matroska",
+        }
+    }
+}
+
+struct ActiveRecording {
+    stop_flag: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    path: PathBuf,
+}
+
+/// Tracks the single in-progress recording, if any. Only one recording
+/// can run at a time; `POST /record/start` while one is active fails
+/// rather than silently starting a second file.
+#[derive(Clone, Default)]
+pub struct RecordingManager {
+    active: Arc<Mutex<Option<ActiveRecording>>>,
+}
+
+impl RecordingManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(
+        &self,
+        frame_tx: &FrameTx,
+        path: PathBuf,
+        container: Container,
+        fps: u32,
+    ) -> Result<(), String> {
+        let mut active = self.active.lock().unwrap();
+        if active.is_some() {
+            return Err("a recording is already in progress".into());
+        }
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let rx = frame_tx.subscribe();
+        let thread_stop_flag = stop_flag.clone();
+        let thread_path = path.clone();
+
+        let handle = thread::spawn(move || {
+            if let Err(e) = record_loop(rx, &thread_path, container, fps, thread_stop_flag) {
+                eprintln!("Recording error: {}", e);
+            }
+        });
+
+        *active = Some(ActiveRecording { stop_flag, handle, path });
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<PathBuf, String> {
+        let recording = self.active.lock().unwrap().take();
+        match recording {
+            Some(rec) => {
+                rec.stop_flag.store(true, Ordering::SeqCst);
+                let _ = rec.handle.join();
+                Ok(rec.path)
+            }
+            None => Err("no recording in progress".into()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.active.lock().unwrap().is_some()
+    }
+}
+
+fn record_loop(
+    mut rx: broadcast::Receiver<Arc<RawFrame>>,
+    path: &Path,
+    container: Container,
+    fps: u32,
+    stop_flag: Arc<AtomicBool>,
+) -> Result<(), ffmpeg::Error> {
+    ffmpeg::init()?;
+
+    let mut octx = ffmpeg::format::output_as(path, container.format_name())?;
+    let mut encoder = None;
+    let mut scaler = None;
+    let mut stream_index = 0usize;
+    // Capture is event-driven (no frame at all while the desktop is idle),
+    // so PTS must track actual elapsed time, not a frame counter at a
+    // fixed-FPS time base, or idle stretches play back time-compressed.
+    let mut start_time: Option<Instant> = None;
+    let time_base = ffmpeg::Rational(1, fps as i32);
+
+    // Fragmented so the file on disk is always playable, even if we're
+    // killed before `write_trailer`.
+    let mut mux_opts = ffmpeg::Dictionary::new();
+    if container == Container::Mp4 {
+        mux_opts.set("movflags", "frag_keyframe+empty_moov");
+    }
+
+    let mut header_written = false;
+
+    loop {
+        if stop_flag.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let frame = match rx.blocking_recv() {
+            Ok(frame) => frame,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        let img = match frame.to_dynamic_image() {
+            Some(img) => img,
+            None => {
+                eprintln!("Recording: failed to build image from raw frame");
+                continue;
+            }
+        };
+
+        if encoder.is_none() {
+            let (w, h) = img.dimensions();
+            let (enc, idx) = open_video_stream(&mut octx, w, h, time_base)?;
+            encoder = Some(enc);
+            scaler = Some(ffmpeg::software::scaling::Context::get(
+                ffmpeg::format::Pixel::RGBA,
+                w,
+                h,
+                ffmpeg::format::Pixel::YUV420P,
+                w,
+                h,
+                ffmpeg::software::scaling::Flags::BILINEAR,
+            )?);
+            stream_index = idx;
+            octx.write_header_with(mux_opts.clone())?;
+            header_written = true;
+        }
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(*start_time.get_or_insert(now));
+        let pts = (elapsed.as_nanos() as i64 * fps as i64) / 1_000_000_000;
+
+        let enc = encoder.as_mut().unwrap();
+        let yuv_frame = match to_yuv420p(&img, scaler.as_mut().unwrap(), pts) {
+            Ok(frame) => frame,
+            Err(e) => {
+                eprintln!("Recording: RGBA->YUV420P scale failed, dropping frame: {:?}", e);
+                continue;
+            }
+        };
+        enc.send_frame(&yuv_frame)?;
+        drain_packets(enc, &mut octx, stream_index, time_base)?;
+    }
+
+    if let Some(mut enc) = encoder {
+        enc.send_eof()?;
+        drain_packets(&mut enc, &mut octx, stream_index, time_base)?;
+    }
+    if header_written {
+        octx.write_trailer()?;
+    }
+
+    Ok(())
+}
+
+fn open_video_stream(
+    octx: &mut ffmpeg::format::context::Output,
+    width: u32,
+    height: u32,
+    time_base: ffmpeg::Rational,
+) -> Result<(ffmpeg::encoder::Video, usize), ffmpeg::Error> {
+    let codec = ffmpeg::encoder::find(ffmpeg::codec::Id::H264)
+        .ok_or(ffmpeg::Error::EncoderNotFound)?;
+    let mut stream = octx.add_stream(codec)?;
+    let stream_index = stream.index();
+
+    let mut encoder = ffmpeg::codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()?;
+    encoder.set_width(width);
+    encoder.set_height(height);
+    encoder.set_format(ffmpeg::format::Pixel::YUV420P);
+    encoder.set_time_base(time_base);
+    if octx.format().flags().contains(ffmpeg::format::Flags::GLOBAL_HEADER) {
+        encoder.set_flags(ffmpeg::codec::Flags::GLOBAL_HEADER);
+    }
+
+    let encoder = encoder.open_as(codec)?;
+    stream.set_parameters(&encoder);
+
+    Ok((encoder, stream_index))
+}
+
+/// Convert a decoded RGBA frame to planar YUV 4:2:0 via `ffmpeg`'s
+/// software scaler, stamping the given PTS (already expressed in the
+/// encoder's `1/fps` time base — see `record_loop`, which derives it
+/// from wall-clock elapsed time rather than a frame counter). The
+/// scaler is built once when the encoder opens and reused here to
+/// avoid a fresh allocation on every frame.
+fn to_yuv420p(
+    img: &image::DynamicImage,
+    scaler: &mut ffmpeg::software::scaling::Context,
+    pts: i64,
+) -> Result<ffmpeg::frame::Video, ffmpeg::Error> {
+    let rgba = img.to_rgba8();
+    let (w, h) = rgba.dimensions();
+
+    let mut rgba_frame = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::RGBA, w, h);
+    copy_rgba_into_plane(&mut rgba_frame, &rgba, w, h);
+
+    let mut yuv_frame = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::YUV420P, w, h);
+    scaler.run(&rgba_frame, &mut yuv_frame)?;
+
+    yuv_frame.set_pts(Some(pts));
+    Ok(yuv_frame)
+}
+
+fn drain_packets(
+    encoder: &mut ffmpeg::encoder::Video,
+    octx: &mut ffmpeg::format::context::Output,
+    stream_index: usize,
+    time_base: ffmpeg::Rational,
+) -> Result<(), ffmpeg::Error> {
+    let mut packet = ffmpeg::Packet::empty();
+    while encoder.receive_packet(&mut packet).is_ok() {
+        packet.set_stream(stream_index);
+        packet.rescale_ts(time_base, octx.stream(stream_index).unwrap().time_base());
+        packet.write_interleaved(octx)?;
+    }
+    Ok(())
+}
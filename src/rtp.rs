@@ -0,0 +1,388 @@
+//! RFC 2435 (RTP Payload Format for JPEG-compressed Video) support.
+//!
+//! JPEG-encodes each raw frame broadcast by the capture thread at its
+//! native size and repackages the entropy-coded scan data into RTP/JPEG
+//! packets that a standard player or SFU can depacketize.
+
+use crate::capture::RawFrame;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+/// Practical UDP payload budget so packets stay under common path MTUs.
+pub(crate) const MAX_PAYLOAD: usize = 1400;
+const RTP_VERSION: u8 = 2;
+/// JPEG's static payload type per RFC 3551 §6.
+const PAYLOAD_TYPE_JPEG: u8 = 26;
+const RTP_CLOCK_HZ: u32 = 90_000;
+/// RTP output always sends at native resolution; quality matches the
+/// `image` crate's own JPEG encoder default.
+const RTP_JPEG_QUALITY: u8 = 75;
+
+/// The header bookkeeping (sequence number, timestamp, SSRC, socket)
+/// shared by every RTP payloader this server has, regardless of which
+/// codec's payload format sits on top.
+pub(crate) struct RtpSession {
+    socket: UdpSocket,
+    dest: SocketAddr,
+    ssrc: u32,
+    seq: u16,
+    timestamp: u32,
+    clock_hz: u32,
+}
+
+impl RtpSession {
+    pub(crate) fn new(dest: SocketAddr, clock_hz: u32) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(dest)?;
+        Ok(Self {
+            socket,
+            dest,
+            ssrc: rand_ssrc(),
+            seq: 0,
+            timestamp: 0,
+            clock_hz,
+        })
+    }
+
+    pub(crate) fn dest(&self) -> SocketAddr {
+        self.dest
+    }
+
+    /// Write the fixed 12-byte RTP header (RFC 3550 §5.1) for the next
+    /// packet and advance the sequence number.
+    pub(crate) fn write_header(&mut self, packet: &mut Vec<u8>, payload_type: u8, marker: bool) {
+        packet.push((RTP_VERSION << 6) & 0xC0);
+        packet.push(((marker as u8) << 7) | payload_type);
+        packet.extend_from_slice(&self.seq.to_be_bytes());
+        packet.extend_from_slice(&self.timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        self.seq = self.seq.wrapping_add(1);
+    }
+
+    pub(crate) fn send(&self, packet: &[u8]) -> io::Result<()> {
+        self.socket.send(packet)?;
+        Ok(())
+    }
+
+    /// Advance the timestamp by one frame period at `fps` in this
+    /// session's clock rate.
+    pub(crate) fn advance_timestamp(&mut self, fps: u32) {
+        self.timestamp = self.timestamp.wrapping_add(self.clock_hz / fps.max(1));
+    }
+}
+
+/// The handful of fields RFC 2435 needs that aren't already implicit in
+/// the compressed bytes: dimensions (in 8-pixel units), the chroma
+/// subsampling (RFC 2435's `type` byte), the quantization tables, and
+/// where the entropy-coded scan actually starts.
+struct JfifInfo {
+    width_blocks: u8,
+    height_blocks: u8,
+    /// RFC 2435 §3.1 `Type`: 0 for 4:2:2, 1 for 4:2:0.
+    subsampling_type: u8,
+    q_tables: Vec<u8>,
+    scan_offset: usize,
+}
+
+/// Walk the JFIF marker segments to pull out what RFC 2435 needs.
+///
+/// Returns `None` if the buffer isn't a baseline JPEG we know how to
+/// payload: progressive scans, arithmetic coding, a missing SOF/SOS, a
+/// chroma subsampling other than 4:2:2/4:2:0 (RFC 2435 has no `type` for
+/// it), or dimensions too large for the format's one-byte block-count
+/// fields (over 2040px) are all out of scope here.
+fn parse_jfif(data: &[u8]) -> Option<JfifInfo> {
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return None; // not a JPEG / missing SOI
+    }
+
+    let mut pos = 2;
+    let mut width = 0u16;
+    let mut height = 0u16;
+    let mut subsampling_type = None;
+    // RFC 2435 wants the luma table followed by the chroma table, 64
+    // bytes each, in the order libjpeg emits DQT segments for baseline.
+    let mut q_tables: Vec<u8> = Vec::new();
+
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None; // lost sync
+        }
+        let marker = data[pos + 1];
+        pos += 2;
+
+        match marker {
+            0xD8 | 0x01 | 0xD0..=0xD7 => continue, // no-length markers
+            0xDA => {
+                // SOS: header length includes itself, scan data follows.
+                let seg_len = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
+                let scan_offset = pos + seg_len;
+                let width_blocks = width / 8;
+                let height_blocks = height / 8;
+                if width_blocks > u8::MAX as u16 || height_blocks > u8::MAX as u16 {
+                    return None; // wouldn't fit RFC 2435's one-byte block counts
+                }
+                return Some(JfifInfo {
+                    width_blocks: width_blocks as u8,
+                    height_blocks: height_blocks as u8,
+                    subsampling_type: subsampling_type?,
+                    q_tables,
+                    scan_offset,
+                });
+            }
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCF => return None, // not baseline DCT
+            _ => {
+                let seg_len = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
+                let seg = &data[pos + 2..pos + seg_len];
+
+                if marker == 0xC0 {
+                    height = u16::from_be_bytes([seg[1], seg[2]]);
+                    width = u16::from_be_bytes([seg[3], seg[4]]);
+                    // First component (by JFIF convention, luma) sampling
+                    // factors: high nibble horizontal, low nibble vertical.
+                    if seg.len() >= 8 {
+                        let (h, v) = (seg[7] >> 4, seg[7] & 0x0F);
+                        subsampling_type = match (h, v) {
+                            (2, 2) => Some(1), // 4:2:0
+                            (2, 1) | (1, 2) => Some(0), // 4:2:2
+                            _ => None, // e.g. 4:4:4: RFC 2435 has no type for it
+                        };
+                    }
+                } else if marker == 0xDB {
+                    // One segment can carry multiple 65-byte (id + 64) tables.
+                    let mut i = 0;
+                    while i + 65 <= seg.len() {
+                        q_tables.extend_from_slice(&seg[i + 1..i + 65]);
+                        i += 65;
+                    }
+                }
+
+                pos += seg_len;
+            }
+        }
+    }
+
+    None
+}
+
+/// Streams frames to a UDP endpoint as RFC 2435 RTP/JPEG packets.
+pub struct RtpJpegSender {
+    session: RtpSession,
+    fps: u32,
+    /// Whether we've already logged a skipped-frame reason. Frames from
+    /// one capture thread share a resolution and subsampling, so once a
+    /// frame is unpayloadable every frame after it usually is too —
+    /// logging it once is enough; repeating it every frame would just
+    /// spam the log without telling the operator anything new.
+    warned_skip: bool,
+}
+
+impl RtpJpegSender {
+    pub fn new(dest: SocketAddr, fps: u32) -> io::Result<Self> {
+        Ok(Self {
+            session: RtpSession::new(dest, RTP_CLOCK_HZ)?,
+            fps: fps.max(1),
+            warned_skip: false,
+        })
+    }
+
+    /// JPEG-encode and send one captured frame, fragmenting it across
+    /// as many RTP packets as `MAX_PAYLOAD` requires. Quantization
+    /// tables are only carried on the first fragment, per RFC 2435
+    /// §3.1.8.
+    pub fn send_frame(&mut self, frame: &RawFrame) -> io::Result<()> {
+        let jpeg_data = match frame.encode_jpeg(None, None, RTP_JPEG_QUALITY) {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+
+        let info = match parse_jfif(&jpeg_data) {
+            Some(info) => info,
+            None => {
+                if !self.warned_skip {
+                    eprintln!(
+                        "RTP: skipping frame(s), not payloadable as RFC 2435 (not baseline, \
+                         unsupported chroma subsampling, or resolution over the format's \
+                         2040x2040-pixel block-count limit — e.g. any 1440p/4K capture); \
+                         further skips this session won't be logged individually"
+                    );
+                    self.warned_skip = true;
+                }
+                return Ok(());
+            }
+        };
+
+        let scan = &jpeg_data[info.scan_offset..];
+        let mut offset = 0usize;
+
+        while offset < scan.len() {
+            let remaining = scan.len() - offset;
+            let chunk_len = remaining.min(MAX_PAYLOAD);
+            let chunk = &scan[offset..offset + chunk_len];
+            let is_first = offset == 0;
+            let is_last = offset + chunk_len == scan.len();
+
+            let mut packet = Vec::with_capacity(12 + 8 + info.q_tables.len() + chunk.len());
+            self.session.write_header(&mut packet, PAYLOAD_TYPE_JPEG, is_last);
+            Self::write_jpeg_header(&mut packet, &info, offset, is_first);
+            packet.extend_from_slice(chunk);
+
+            self.session.send(&packet)?;
+            offset += chunk_len;
+        }
+
+        self.session.advance_timestamp(self.fps);
+        Ok(())
+    }
+
+    /// RFC 2435 §3.1: the 8-byte main JPEG header, plus the restart
+    /// marker header (unused here) and quantization table header when
+    /// this is the first fragment of the frame. `Q` must be identical
+    /// across every fragment of a frame — receivers key scan reassembly
+    /// and dynamic-table association on it — so it's derived from
+    /// `info` alone, never from `is_first`; only the table *header
+    /// block itself* is gated on being the first fragment.
+    fn write_jpeg_header(packet: &mut Vec<u8>, info: &JfifInfo, frag_offset: usize, is_first: bool) {
+        packet.push(0); // type-specific
+        let off = frag_offset as u32;
+        packet.push((off >> 16) as u8);
+        packet.push((off >> 8) as u8);
+        packet.push(off as u8);
+        packet.push(info.subsampling_type); // 0: 4:2:2, 1: 4:2:0; no restart markers
+        let q = if info.q_tables.is_empty() { 128 } else { 255 };
+        packet.push(q);
+        packet.push(info.width_blocks);
+        packet.push(info.height_blocks);
+
+        if is_first && !info.q_tables.is_empty() {
+            packet.push(0); // MBZ
+            packet.push(0); // precision: 8-bit entries
+            let len = info.q_tables.len() as u16;
+            packet.extend_from_slice(&len.to_be_bytes());
+            packet.extend_from_slice(&info.q_tables);
+        }
+    }
+
+    pub fn dest(&self) -> SocketAddr {
+        self.session.dest()
+    }
+}
+
+/// Cheap, non-cryptographic SSRC; uniqueness across one process is all
+/// RFC 3550 asks of us here.
+fn rand_ssrc() -> u32 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    nanos ^ std::process::id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hand-build just enough of a baseline JPEG's marker segments for
+    /// `parse_jfif` to walk: SOI, one DQT with `num_tables` 64-byte
+    /// tables, an SOF0 at `width`x`height` with the first component's
+    /// sampling factors set to `h_sample`/`v_sample`, and an SOS with a
+    /// few bytes of fake scan data after it.
+    fn build_jpeg(width: u16, height: u16, h_sample: u8, v_sample: u8, num_tables: usize) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8]; // SOI
+
+        if num_tables > 0 {
+            let mut dqt = vec![0xFF, 0xDB];
+            let seg_len = 2 + num_tables * 65;
+            dqt.extend_from_slice(&(seg_len as u16).to_be_bytes());
+            for id in 0..num_tables {
+                dqt.push(id as u8);
+                dqt.extend(std::iter::repeat(1u8).take(64));
+            }
+            data.extend(dqt);
+        }
+
+        let mut sof0 = vec![0xFF, 0xC0];
+        // precision(1) + height(2) + width(2) + num_components(1) + 1
+        // component * (id(1) + sampling(1) + qtable_id(1)), plus the
+        // length field itself.
+        let seg_len: u16 = 2 + 1 + 2 + 2 + 1 + 3;
+        sof0.extend_from_slice(&seg_len.to_be_bytes());
+        sof0.push(8); // precision
+        sof0.extend_from_slice(&height.to_be_bytes());
+        sof0.extend_from_slice(&width.to_be_bytes());
+        sof0.push(1); // num_components
+        sof0.push(1); // component id
+        sof0.push((h_sample << 4) | v_sample);
+        sof0.push(0); // qtable id
+        data.extend(sof0);
+
+        let scan_data = [0xAA, 0xBB, 0xCC, 0xDD];
+        let mut sos = vec![0xFF, 0xDA];
+        let seg_len: u16 = 2 + 6; // header length only, doesn't cover scan data
+        sos.extend_from_slice(&seg_len.to_be_bytes());
+        sos.extend_from_slice(&[0u8; 6]); // num_components(1) + 1*(id+table) + 3 trailing bytes
+        data.extend(sos);
+        data.extend_from_slice(&scan_data);
+
+        data
+    }
+
+    #[test]
+    fn parse_jfif_reads_dimensions_subsampling_and_tables() {
+        let data = build_jpeg(64, 32, 2, 2, 2);
+        let info = parse_jfif(&data).expect("well-formed baseline JPEG should parse");
+
+        assert_eq!(info.width_blocks, 64 / 8);
+        assert_eq!(info.height_blocks, 32 / 8);
+        assert_eq!(info.subsampling_type, 1); // 4:2:0
+        assert_eq!(info.q_tables.len(), 2 * 64);
+        assert_eq!(&data[info.scan_offset..], &[0xAA, 0xBB, 0xCC, 0xDD]);
+    }
+
+    #[test]
+    fn parse_jfif_maps_422_sampling_to_type_0() {
+        let data = build_jpeg(16, 16, 2, 1, 0);
+        let info = parse_jfif(&data).expect("well-formed baseline JPEG should parse");
+        assert_eq!(info.subsampling_type, 0);
+    }
+
+    #[test]
+    fn parse_jfif_rejects_unsupported_subsampling() {
+        let data = build_jpeg(16, 16, 1, 1, 0); // 4:4:4, no RFC 2435 type
+        assert!(parse_jfif(&data).is_none());
+    }
+
+    #[test]
+    fn parse_jfif_rejects_dimensions_over_the_block_count_limit() {
+        // 2048px is just past the 2040px (u8::MAX * 8) block-count cap.
+        let data = build_jpeg(2048, 16, 2, 2, 0);
+        assert!(parse_jfif(&data).is_none());
+    }
+
+    #[test]
+    fn parse_jfif_rejects_non_jpeg_input() {
+        assert!(parse_jfif(&[0x00, 0x01, 0x02, 0x03]).is_none());
+    }
+
+    #[test]
+    fn write_jpeg_header_keeps_q_constant_across_fragments() {
+        let info = JfifInfo {
+            width_blocks: 4,
+            height_blocks: 4,
+            subsampling_type: 1,
+            q_tables: vec![1; 64],
+            scan_offset: 0,
+        };
+
+        let mut first = Vec::new();
+        RtpJpegSender::write_jpeg_header(&mut first, &info, 0, true);
+        let mut later = Vec::new();
+        RtpJpegSender::write_jpeg_header(&mut later, &info, 1024, false);
+
+        // Byte 5 (after type-specific, 3-byte offset and type) is Q; RFC 2435
+        // requires it identical on every fragment of a frame.
+        assert_eq!(first[5], later[5]);
+        assert_eq!(first[5], 255); // q_tables non-empty, so Q signals a table header
+    }
+}
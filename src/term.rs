@@ -0,0 +1,199 @@
+//! Headless terminal preview: renders the live capture directly into a
+//! terminal (over SSH, with no browser available) using either the
+//! Kitty graphics protocol or DEC sixel.
+
+use base64::Engine;
+use image::{DynamicImage, GenericImageView};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TermProtocol {
+    Kitty,
+    Sixel,
+}
+
+/// How to fit the capture into the terminal: a target cell grid plus
+/// the pixel size of one cell, so we know how many source pixels to
+/// downscale to.
+#[derive(Clone, Copy, Debug)]
+pub struct TermConfig {
+    pub cols: u32,
+    pub rows: u32,
+    pub cell_pixel_w: u32,
+    pub cell_pixel_h: u32,
+    pub protocol: TermProtocol,
+}
+
+impl TermConfig {
+    fn target_pixels(&self) -> (u32, u32) {
+        ((self.cols * self.cell_pixel_w).max(1), (self.rows * self.cell_pixel_h).max(1))
+    }
+}
+
+/// Downscale the already-decoded frame to fit the terminal and emit the
+/// escape sequence for the configured protocol, homing the cursor first
+/// so each frame redraws in place instead of scrolling.
+pub fn render_frame(img: &DynamicImage, config: &TermConfig) -> String {
+    let (w, h) = config.target_pixels();
+    let resized = img.resize_exact(w, h, image::imageops::FilterType::Triangle);
+
+    let body = match config.protocol {
+        TermProtocol::Kitty => render_kitty(&resized),
+        TermProtocol::Sixel => render_sixel(&resized),
+    };
+    format!("\x1b[H{}", body)
+}
+
+/// Kitty graphics protocol: a base64 RGBA payload, chunked so no escape
+/// sequence exceeds the terminal's line-length limit, with `m=1` on all
+/// but the final chunk.
+fn render_kitty(img: &DynamicImage) -> String {
+    const CHUNK_SIZE: usize = 4096;
+
+    let rgba = img.to_rgba8();
+    let (w, h) = rgba.dimensions();
+    let payload = base64::engine::general_purpose::STANDARD.encode(rgba.as_raw());
+    let chunks = payload.as_bytes().chunks(CHUNK_SIZE);
+    let chunk_count = chunks.len().max(1);
+
+    let mut out = String::new();
+    for (i, chunk) in payload.as_bytes().chunks(CHUNK_SIZE).enumerate() {
+        let more = if i + 1 < chunk_count { 1 } else { 0 };
+        if i == 0 {
+            out.push_str(&format!("\x1b_Ga=T,f=32,s={},v={},m={};", w, h, more));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};", more));
+        }
+        out.push_str(std::str::from_utf8(chunk).expect("base64 alphabet is ASCII"));
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+/// A 6x6x6 color cube plus a 24-step grayscale ramp (the same layout as
+/// the xterm 256-color palette), used to quantize pixels for sixel.
+fn build_cube_palette() -> Vec<(u8, u8, u8)> {
+    let levels: [u8; 6] = [0, 51, 102, 153, 204, 255];
+    let mut palette = Vec::with_capacity(240);
+
+    for r in levels {
+        for g in levels {
+            for b in levels {
+                palette.push((r, g, b));
+            }
+        }
+    }
+    for i in 0..24 {
+        let v = (8 + i * 10) as u8;
+        palette.push((v, v, v));
+    }
+
+    palette
+}
+
+fn nearest_palette_index(palette: &[(u8, u8, u8)], r: u8, g: u8, b: u8) -> usize {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, &(pr, pg, pb))| {
+            let dr = r as i32 - pr as i32;
+            let dg = g as i32 - pg as i32;
+            let db = b as i32 - pb as i32;
+            dr * dr + dg * dg + db * db
+        })
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// DEC sixel bytes encode 100% (0-255) components as 0-100 percent.
+fn to_percent(component: u8) -> u32 {
+    (component as u32 * 100 + 127) / 255
+}
+
+/// Quantize to the cube palette and emit a DEC sixel image: a palette
+/// preamble (`#n;2;r;g;b`), then one 6-pixel-tall band at a time, one
+/// run of sixel characters per color used in that band.
+fn render_sixel(img: &DynamicImage) -> String {
+    let rgba = img.to_rgba8();
+    let (w, h) = rgba.dimensions();
+    let palette = build_cube_palette();
+
+    let mut out = String::new();
+    out.push_str("\x1bPq");
+    for (i, &(r, g, b)) in palette.iter().enumerate() {
+        out.push_str(&format!("#{};2;{};{};{}", i, to_percent(r), to_percent(g), to_percent(b)));
+    }
+
+    let band_count = (h + 5) / 6;
+    for band in 0..band_count {
+        let y0 = band * 6;
+        let rows_in_band = (h - y0).min(6);
+
+        // One sixel row (bitmask over 6 vertical pixels) per palette
+        // color, for every column in this band.
+        let mut color_rows = vec![vec![0u8; w as usize]; palette.len()];
+        for x in 0..w {
+            for ry in 0..rows_in_band {
+                let pixel = rgba.get_pixel(x, y0 + ry);
+                let idx = nearest_palette_index(&palette, pixel[0], pixel[1], pixel[2]);
+                color_rows[idx][x as usize] |= 1 << ry;
+            }
+        }
+
+        let mut wrote_any = false;
+        for (idx, row) in color_rows.iter().enumerate() {
+            if row.iter().all(|&bits| bits == 0) {
+                continue;
+            }
+            if wrote_any {
+                out.push('$'); // return to start of this band, next color
+            }
+            out.push_str(&format!("#{}", idx));
+            out.extend(row.iter().map(|&bits| (bits + 63) as char));
+            wrote_any = true;
+        }
+        out.push('-'); // advance to the next band
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_cube_palette_has_216_cube_entries_plus_24_gray_steps() {
+        let palette = build_cube_palette();
+        assert_eq!(palette.len(), 216 + 24);
+        assert_eq!(palette[0], (0, 0, 0));
+        assert_eq!(palette[215], (255, 255, 255));
+    }
+
+    #[test]
+    fn nearest_palette_index_picks_the_closest_entry() {
+        let palette = vec![(0, 0, 0), (255, 255, 255), (128, 0, 0)];
+        assert_eq!(nearest_palette_index(&palette, 10, 5, 0), 0);
+        assert_eq!(nearest_palette_index(&palette, 250, 250, 250), 1);
+        assert_eq!(nearest_palette_index(&palette, 130, 10, 5), 2);
+    }
+
+    #[test]
+    fn nearest_palette_index_returns_zero_for_an_empty_palette() {
+        assert_eq!(nearest_palette_index(&[], 1, 2, 3), 0);
+    }
+
+    #[test]
+    fn to_percent_maps_the_full_byte_range_to_0_100() {
+        assert_eq!(to_percent(0), 0);
+        assert_eq!(to_percent(255), 100);
+        assert_eq!(to_percent(128), 50);
+    }
+
+    #[test]
+    fn render_sixel_emits_dcs_header_and_terminator() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(4, 4, image::Rgba([200, 0, 0, 255])));
+        let out = render_sixel(&img);
+        assert!(out.starts_with("\x1bPq"));
+        assert!(out.ends_with("\x1b\\"));
+    }
+}
@@ -0,0 +1,292 @@
+//! VP8/VP9 encoding for bandwidth-efficient browser/WebRTC delivery.
+//!
+//! Unlike the MJPEG and RFC 2435 RTP outputs, this path is inter-frame
+//! compressed, so (unlike JPEG re-encoding) `VpEncoder` carries real
+//! state across frames and always needs a keyframe to bootstrap: one is
+//! forced when it's first built, and `force_keyframe` lets a caller ask
+//! for another later (periodically, in lieu of real RTCP loss feedback —
+//! see `main.rs`). The RTP sender below targets a single fixed UDP
+//! destination configured at startup, not a set of dynamically joining
+//! WebRTC peers, so today there is exactly one `VpEncoder` driving it
+//! rather than one per subscriber; per-subscriber encoders are the
+//! natural extension once this gains real peer connection handling.
+
+use crate::capture::RawFrame;
+use crate::ffmpeg_util::copy_rgba_into_plane;
+use crate::rtp::{RtpSession, MAX_PAYLOAD};
+use ffmpeg_next as ffmpeg;
+use std::io;
+use std::net::SocketAddr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VpCodec {
+    Vp8,
+    Vp9,
+}
+
+impl VpCodec {
+    fn ffmpeg_id(&self) -> ffmpeg::codec::Id {
+        match self {
+            VpCodec::Vp8 => ffmpeg::codec::Id::VP8,
+            VpCodec::Vp9 => ffmpeg::codec::Id::VP9,
+        }
+    }
+
+    /// Dynamic payload type numbers, picked independently of each
+    /// other; VP9 is only reserved here for `VpEncoder`'s sake — see
+    /// `VpRtpSender::new`, which doesn't actually packetize it over RTP.
+    fn rtp_payload_type(&self) -> u8 {
+        match self {
+            VpCodec::Vp8 => 96,
+            VpCodec::Vp9 => 98,
+        }
+    }
+}
+
+/// One encoded frame, plus whether it's a keyframe (a subscriber that
+/// just (re)joined, or just missed packets, needs to wait for one).
+pub struct EncodedChunk {
+    pub data: Vec<u8>,
+    pub is_keyframe: bool,
+}
+
+/// A stateful VP8/VP9 encoder built on `ffmpeg-next`'s
+/// software encoders.
+pub struct VpEncoder {
+    encoder: ffmpeg::encoder::Video,
+    scaler: ffmpeg::software::scaling::Context,
+    width: u32,
+    height: u32,
+    frame_count: i64,
+    force_keyframe: bool,
+}
+
+impl VpEncoder {
+    pub fn new(codec: VpCodec, width: u32, height: u32, fps: u32, bitrate: usize) -> Result<Self, ffmpeg::Error> {
+        ffmpeg::init()?;
+
+        let ff_codec = ffmpeg::encoder::find(codec.ffmpeg_id()).ok_or(ffmpeg::Error::EncoderNotFound)?;
+        let mut ctx = ffmpeg::codec::context::Context::new_with_codec(ff_codec)
+            .encoder()
+            .video()?;
+        ctx.set_width(width);
+        ctx.set_height(height);
+        ctx.set_format(ffmpeg::format::Pixel::YUV420P);
+        ctx.set_time_base(ffmpeg::Rational(1, fps as i32));
+        ctx.set_bit_rate(bitrate);
+        let encoder = ctx.open_as(ff_codec)?;
+
+        let scaler = ffmpeg::software::scaling::Context::get(
+            ffmpeg::format::Pixel::RGBA,
+            width,
+            height,
+            ffmpeg::format::Pixel::YUV420P,
+            width,
+            height,
+            ffmpeg::software::scaling::Flags::BILINEAR,
+        )?;
+
+        Ok(Self {
+            encoder,
+            scaler,
+            width,
+            height,
+            frame_count: 0,
+            force_keyframe: true, // first frame out of a fresh encoder is always a keyframe
+        })
+    }
+
+    /// Request that the *next* `encode` call produce a keyframe: used
+    /// as a periodic safety net against whatever packet loss we have no
+    /// RTCP feedback to detect (a fresh encoder already starts with one).
+    pub fn force_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    /// Encode one captured frame, returning every packet the encoder
+    /// flushed for it (usually zero or one, since these codecs are
+    /// typically one-frame-in-one-frame-out without B-frames here).
+    pub fn encode(&mut self, frame: &RawFrame) -> Vec<EncodedChunk> {
+        if frame.width != self.width || frame.height != self.height {
+            eprintln!("VP8/VP9: frame size changed mid-stream, dropping frame");
+            return Vec::new();
+        }
+
+        let mut rgba_frame = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::RGBA, self.width, self.height);
+        copy_rgba_into_plane(&mut rgba_frame, &frame.rgba, self.width, self.height);
+
+        let mut yuv_frame = ffmpeg::frame::Video::new(ffmpeg::format::Pixel::YUV420P, self.width, self.height);
+        if let Err(e) = self.scaler.run(&rgba_frame, &mut yuv_frame) {
+            eprintln!("VP8/VP9: RGBA->YUV420P scale failed: {:?}", e);
+            return Vec::new();
+        }
+        yuv_frame.set_pts(Some(self.frame_count));
+        if self.force_keyframe {
+            yuv_frame.set_kind(ffmpeg::picture::Type::I);
+            self.force_keyframe = false;
+        }
+
+        if let Err(e) = self.encoder.send_frame(&yuv_frame) {
+            eprintln!("VP8/VP9 encode error: {:?}", e);
+            return Vec::new();
+        }
+        self.frame_count += 1;
+
+        let mut chunks = Vec::new();
+        let mut packet = ffmpeg::Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            if let Some(data) = packet.data() {
+                chunks.push(EncodedChunk {
+                    data: data.to_vec(),
+                    is_keyframe: packet.is_key(),
+                });
+            }
+        }
+        chunks
+    }
+}
+
+/// Streams VP8 frames to a UDP endpoint, packetized per RFC 7741's VP8
+/// payload descriptor with the picture ID extension (so a receiver can
+/// detect missing frames, not just missing fragments). VP9 has its own
+/// distinct RTP payload format (a different descriptor layout, not this
+/// one), which this sender doesn't implement yet, so `new` rejects it
+/// rather than emit bytes a VP9 depacketizer would misparse.
+pub struct VpRtpSender {
+    session: RtpSession,
+    payload_type: u8,
+    fps: u32,
+    picture_id: u16,
+}
+
+impl VpRtpSender {
+    pub fn new(dest: SocketAddr, codec: VpCodec, fps: u32) -> io::Result<Self> {
+        if codec == VpCodec::Vp9 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "VP9 RTP packetization is not implemented; use VP8 for the RTP output",
+            ));
+        }
+
+        Ok(Self {
+            session: RtpSession::new(dest, 90_000)?,
+            payload_type: codec.rtp_payload_type(),
+            fps: fps.max(1),
+            picture_id: 0,
+        })
+    }
+
+    pub fn send_chunk(&mut self, chunk: &EncodedChunk) -> io::Result<()> {
+        if chunk.data.is_empty() {
+            return Ok(());
+        }
+
+        // One picture ID per encoded frame, shared by every fragment of it.
+        let picture_id = self.picture_id & 0x7fff;
+        self.picture_id = self.picture_id.wrapping_add(1);
+
+        let mut offset = 0usize;
+        while offset < chunk.data.len() {
+            let remaining = chunk.data.len() - offset;
+            let payload_len = remaining.min(MAX_PAYLOAD);
+            let is_first = offset == 0;
+            let is_last = offset + payload_len == chunk.data.len();
+
+            let mut packet = Vec::with_capacity(12 + 4 + payload_len);
+            self.session.write_header(&mut packet, self.payload_type, is_last);
+            // VP8 payload descriptor: X=1 (extension follows), S=start-of-partition
+            // on the first fragment only, PID=0 (single-partition encoder output).
+            packet.push(0x80 | if is_first { 0x10 } else { 0x00 });
+            // Extension byte: I=1 (picture ID follows), everything else unused.
+            packet.push(0x80);
+            // 15-bit picture ID (M=1 selects the two-byte form), big-endian.
+            packet.push(0x80 | (picture_id >> 8) as u8);
+            packet.push((picture_id & 0xff) as u8);
+            packet.extend_from_slice(&chunk.data[offset..offset + payload_len]);
+
+            self.session.send(&packet)?;
+            offset += payload_len;
+        }
+
+        self.session.advance_timestamp(self.fps);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    /// Drive a real loopback `VpRtpSender` so the packets it emits go
+    /// through the same header/descriptor code `send_chunk` uses in
+    /// production, rather than duplicating that logic in the test.
+    fn sender_and_receiver() -> (VpRtpSender, UdpSocket) {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let dest = receiver.local_addr().unwrap();
+        let sender = VpRtpSender::new(dest, VpCodec::Vp8, 30).unwrap();
+        (sender, receiver)
+    }
+
+    fn recv_packet(receiver: &UdpSocket) -> Vec<u8> {
+        let mut buf = [0u8; 2048];
+        let n = receiver.recv(&mut buf).unwrap();
+        buf[..n].to_vec()
+    }
+
+    #[test]
+    fn new_rejects_vp9() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let dest = receiver.local_addr().unwrap();
+        assert!(VpRtpSender::new(dest, VpCodec::Vp9, 30).is_err());
+    }
+
+    #[test]
+    fn single_fragment_chunk_sets_start_bit_and_marker() {
+        let (mut sender, receiver) = sender_and_receiver();
+        let chunk = EncodedChunk { data: vec![1, 2, 3], is_keyframe: true };
+        sender.send_chunk(&chunk).unwrap();
+
+        let packet = recv_packet(&receiver);
+        // RTP header: marker bit is the top bit of byte 1; a single-fragment
+        // chunk is also the last fragment, so it must be set.
+        assert_eq!(packet[1] & 0x80, 0x80);
+
+        let descriptor = &packet[12..];
+        assert_eq!(descriptor[0], 0x80 | 0x10); // X=1, S=1 (start of partition)
+        assert_eq!(descriptor[1], 0x80); // extension byte: I=1
+        assert_eq!(descriptor[2] & 0x80, 0x80); // picture ID M=1 (two-byte form)
+        assert_eq!(&descriptor[4..], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn multi_fragment_chunk_sets_start_bit_only_on_the_first_packet() {
+        let (mut sender, receiver) = sender_and_receiver();
+        let data = vec![0u8; MAX_PAYLOAD + 10];
+        let chunk = EncodedChunk { data, is_keyframe: false };
+        sender.send_chunk(&chunk).unwrap();
+
+        let first = recv_packet(&receiver);
+        let second = recv_packet(&receiver);
+
+        assert_eq!(first[1] & 0x80, 0); // not the last fragment yet
+        assert_eq!(first[12] & 0x10, 0x10); // S=1 on the first fragment
+        assert_eq!(second[1] & 0x80, 0x80); // last fragment carries the marker
+        assert_eq!(second[12] & 0x10, 0); // S=0 on later fragments
+    }
+
+    #[test]
+    fn picture_id_increments_once_per_frame_not_per_fragment() {
+        let (mut sender, receiver) = sender_and_receiver();
+        let data = vec![0u8; MAX_PAYLOAD + 10]; // two fragments
+        sender.send_chunk(&EncodedChunk { data, is_keyframe: false }).unwrap();
+        let first = recv_packet(&receiver);
+        let second = recv_packet(&receiver);
+        let pic_id = |p: &[u8]| (((p[14] & 0x7f) as u16) << 8) | p[15] as u16;
+        assert_eq!(pic_id(&first), pic_id(&second));
+
+        sender.send_chunk(&EncodedChunk { data: vec![9], is_keyframe: false }).unwrap();
+        let third = recv_packet(&receiver);
+        assert_eq!(pic_id(&third), pic_id(&first) + 1);
+    }
+}